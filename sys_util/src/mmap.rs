@@ -10,6 +10,8 @@ use std::fmt::{self, Display};
 use std::io::{Read, Write};
 use std::os::unix::io::AsRawFd;
 use std::ptr::null_mut;
+use std::sync::atomic::{fence, Ordering};
+use std::sync::OnceLock;
 
 use data_model::volatile_memory::*;
 use data_model::DataInit;
@@ -17,6 +19,38 @@ use libc::{self, c_int};
 
 use crate::errno;
 
+/// Fallback L1 data cacheline size used when the host can't tell us its real one.
+const DEFAULT_CACHELINE_SIZE: usize = 64;
+
+/// Returns the L1 data cacheline size of the host, querying it once and caching the result.
+fn cacheline_size() -> usize {
+    static CACHELINE_SIZE: OnceLock<usize> = OnceLock::new();
+    *CACHELINE_SIZE.get_or_init(|| {
+        // `_SC_LEVEL1_DCACHE_LINESIZE` isn't defined by `libc` on musl, so there is no query to
+        // make there; just take the fallback below.
+        #[cfg(not(target_env = "musl"))]
+        {
+            // Safe because this call just queries the kernel for a constant and doesn't touch any
+            // memory of its own.
+            let line_size = unsafe { libc::sysconf(libc::_SC_LEVEL1_DCACHE_LINESIZE) };
+            if line_size > 0 {
+                return line_size as usize;
+            }
+        }
+        DEFAULT_CACHELINE_SIZE
+    })
+}
+
+/// Returns the host's page size, querying it once and caching the result.
+fn page_size() -> usize {
+    static PAGE_SIZE: OnceLock<usize> = OnceLock::new();
+    *PAGE_SIZE.get_or_init(|| {
+        // Safe because this call just queries the kernel for a constant and doesn't touch any
+        // memory of its own.
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+    })
+}
+
 #[derive(Debug)]
 pub enum Error {
     /// Requested memory out of range.
@@ -97,6 +131,26 @@ impl Into<c_int> for Protection {
     }
 }
 
+/// Explicit huge page size to request for a hugetlbfs-backed mapping.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum HugePageSize {
+    /// 2 MiB huge pages.
+    Size2Mb,
+    /// 1 GiB huge pages.
+    Size1Gb,
+}
+
+impl HugePageSize {
+    // The page size is encoded in the upper bits of the mmap flags, shifted by
+    // MAP_HUGE_SHIFT. See the mmap(2) MAP_HUGETLB section for details.
+    fn to_mmap_flags(self) -> c_int {
+        match self {
+            HugePageSize::Size2Mb => libc::MAP_HUGE_2MB,
+            HugePageSize::Size1Gb => libc::MAP_HUGE_1GB,
+        }
+    }
+}
+
 /// Wraps an anonymous shared memory mapping in the current process.
 #[derive(Debug)]
 pub struct MemoryMapping {
@@ -149,6 +203,61 @@ impl MemoryMapping {
                 errno::Error::last()
             );
         }
+        // This is safe for the same reason as the MADV_DONTDUMP call above. Asking for
+        // transparent huge pages is just a hint, so a failure here is not fatal either.
+        if unsafe { libc::madvise(addr, size, libc::MADV_HUGEPAGE) } == -1 {
+            warn!(
+                "failed madvise(MADV_HUGEPAGE) on mmap: {}",
+                errno::Error::last()
+            );
+        }
+        Ok(MemoryMapping {
+            addr: addr as *mut u8,
+            size,
+        })
+    }
+
+    /// Creates an anonymous shared mapping of `size` bytes with `prot` protection, backed by
+    /// huge pages from hugetlbfs rather than the regular 4 KiB pages. `huge_page_size` selects
+    /// an explicit page size; `None` lets the kernel pick its default hugetlbfs page size.
+    ///
+    /// Callers should fall back to `new_protection` if this returns an error, e.g. because no
+    /// huge pages are reserved on the host.
+    ///
+    /// # Arguments
+    /// * `size` - Size of memory region in bytes.
+    /// * `prot` - Protection (e.g. readable/writable) of the memory region.
+    /// * `huge_page_size` - Explicit huge page size to request, if any.
+    pub fn new_protection_hugetlb(
+        size: usize,
+        prot: Protection,
+        huge_page_size: Option<HugePageSize>,
+    ) -> Result<MemoryMapping> {
+        let huge_flags = libc::MAP_HUGETLB
+            | huge_page_size.map_or(0, |huge_page_size| huge_page_size.to_mmap_flags());
+        // This is safe because we are creating an anonymous mapping in a place not already used
+        // by any other area in this process.
+        let addr = unsafe {
+            libc::mmap(
+                null_mut(),
+                size,
+                prot.into(),
+                libc::MAP_ANONYMOUS | libc::MAP_SHARED | libc::MAP_NORESERVE | huge_flags,
+                -1,
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(Error::SystemCallFailed(errno::Error::last()));
+        }
+        // This is safe because we call madvise with a valid address and size, and we check the
+        // return value. We only warn about an error because failure here is not fatal to the mmap.
+        if unsafe { libc::madvise(addr, size, libc::MADV_DONTDUMP) } == -1 {
+            warn!(
+                "failed madvise(MADV_DONTDUMP) on mmap: {}",
+                errno::Error::last()
+            );
+        }
         Ok(MemoryMapping {
             addr: addr as *mut u8,
             size,
@@ -171,6 +280,44 @@ impl MemoryMapping {
     /// * `size` - Size of memory region in bytes.
     /// * `offset` - Offset in bytes from the beginning of `fd` to start the mmap.
     pub fn from_fd_offset(fd: &AsRawFd, size: usize, offset: usize) -> Result<MemoryMapping> {
+        MemoryMapping::from_fd_offset_protection(fd, size, offset, Protection::read_write())
+    }
+
+    /// Maps the `size` bytes starting at `offset` bytes of the given `fd` with `prot`
+    /// protection, as a `MAP_SHARED` mapping.
+    ///
+    /// # Arguments
+    /// * `fd` - File descriptor to mmap from.
+    /// * `size` - Size of memory region in bytes.
+    /// * `offset` - Offset in bytes from the beginning of `fd` to start the mmap.
+    /// * `prot` - Protection (e.g. readable/writable) of the memory region.
+    pub fn from_fd_offset_protection(
+        fd: &AsRawFd,
+        size: usize,
+        offset: usize,
+        prot: Protection,
+    ) -> Result<MemoryMapping> {
+        MemoryMapping::from_fd_offset_protection_flags(fd, size, offset, prot, libc::MAP_SHARED)
+    }
+
+    /// Maps the `size` bytes starting at `offset` bytes of the given `fd` with `prot`
+    /// protection and arbitrary mmap `flags`. Passing `MAP_PRIVATE` in `flags` yields a
+    /// copy-on-write mapping that leaves the underlying file untouched by writes, e.g. for a
+    /// private scratch overlay on a shared disk image or a read-only view of a sealed memfd.
+    ///
+    /// # Arguments
+    /// * `fd` - File descriptor to mmap from.
+    /// * `size` - Size of memory region in bytes.
+    /// * `offset` - Offset in bytes from the beginning of `fd` to start the mmap.
+    /// * `prot` - Protection (e.g. readable/writable) of the memory region.
+    /// * `flags` - mmap flags, e.g. `MAP_SHARED` or `MAP_PRIVATE`.
+    pub fn from_fd_offset_protection_flags(
+        fd: &AsRawFd,
+        size: usize,
+        offset: usize,
+        prot: Protection,
+        flags: c_int,
+    ) -> Result<MemoryMapping> {
         if offset > libc::off_t::max_value() as usize {
             return Err(Error::InvalidOffset);
         }
@@ -180,8 +327,8 @@ impl MemoryMapping {
             libc::mmap(
                 null_mut(),
                 size,
-                libc::PROT_READ | libc::PROT_WRITE,
-                libc::MAP_SHARED,
+                prot.into(),
+                flags,
                 fd.as_raw_fd(),
                 offset as libc::off_t,
             )
@@ -321,6 +468,98 @@ impl MemoryMapping {
         }
     }
 
+    /// Copies `src` into the memory region at `offset` one cacheline at a time, fencing after
+    /// each line so that a concurrent reader spinning on a flag never observes a torn line.
+    /// Unlike `write_slice`, which copies the whole range with a single `memcpy`, this copies at
+    /// most one cacheline per copy (partial lines byte by byte with volatile accesses) and is
+    /// safe to race against another thread (e.g. a device model or migration snapshotter)
+    /// reading the same range with `copy_from_volatile`.
+    ///
+    /// # Arguments
+    /// * `src` - Slice to copy from.
+    /// * `offset` - Begin writing memory at this offset.
+    pub fn copy_to_volatile(&self, src: &[u8], offset: usize) -> Result<()> {
+        self.range_end(offset, src.len())
+            .map_err(|_| Error::InvalidRange(offset, src.len()))?;
+        // Safe because we checked that offset + src.len() is within the bounds of this mapping,
+        // and the destination is only ever handed out through volatile accessors.
+        unsafe {
+            let dst = self.addr.add(offset);
+            Self::volatile_copy(dst, src.as_ptr(), src.len(), dst as usize);
+        }
+        Ok(())
+    }
+
+    /// Copies from the memory region at `offset` into `dst` one cacheline at a time, fencing
+    /// after each line so that a line being concurrently written elsewhere is never observed
+    /// half-updated.
+    ///
+    /// # Arguments
+    /// * `dst` - Slice to copy into.
+    /// * `offset` - Begin reading memory from this offset.
+    pub fn copy_from_volatile(&self, dst: &mut [u8], offset: usize) -> Result<()> {
+        self.range_end(offset, dst.len())
+            .map_err(|_| Error::InvalidRange(offset, dst.len()))?;
+        // Safe because we checked that offset + dst.len() is within the bounds of this mapping,
+        // and the source is only ever handed out through volatile accessors.
+        unsafe {
+            let src = self.addr.add(offset);
+            Self::volatile_copy(dst.as_mut_ptr(), src, dst.len(), src as usize);
+        }
+        Ok(())
+    }
+
+    // Copies `len` bytes from `src` to `dst` in cacheline-sized chunks, issuing a release fence
+    // after each line so that whole cachelines, never partial ones, become visible to observers.
+    // Sub-cacheline head and tail fragments are copied byte-by-byte with volatile accesses.
+    //
+    // Chunk boundaries are computed from `align_addr`, not from `dst`/`src` directly, because the
+    // guest-memory side of the copy is the one being concurrently read or written elsewhere and
+    // is what must land on cacheline boundaries; callers pass the guest-memory address here
+    // regardless of whether it happens to be the source or the destination of this copy.
+    //
+    // Safety: `src` and `dst` must each be valid for `len` bytes and must not overlap.
+    unsafe fn volatile_copy(dst: *mut u8, src: *const u8, len: usize, align_addr: usize) {
+        let line_size = cacheline_size();
+        let mut remaining = len;
+        let mut dst = dst;
+        let mut src = src;
+
+        // Copy the unaligned head fragment, if any, one byte at a time.
+        let misalignment = align_addr % line_size;
+        let head = if misalignment == 0 {
+            0
+        } else {
+            std::cmp::min(line_size - misalignment, remaining)
+        };
+        for i in 0..head {
+            std::ptr::write_volatile(dst.add(i), std::ptr::read_volatile(src.add(i)));
+        }
+        if head > 0 {
+            fence(Ordering::SeqCst);
+            dst = dst.add(head);
+            src = src.add(head);
+            remaining -= head;
+        }
+
+        // Copy whole, aligned cachelines.
+        while remaining >= line_size {
+            std::ptr::copy_nonoverlapping(src, dst, line_size);
+            fence(Ordering::SeqCst);
+            dst = dst.add(line_size);
+            src = src.add(line_size);
+            remaining -= line_size;
+        }
+
+        // Copy the trailing sub-cacheline fragment, if any, one byte at a time.
+        for i in 0..remaining {
+            std::ptr::write_volatile(dst.add(i), std::ptr::read_volatile(src.add(i)));
+        }
+        if remaining > 0 {
+            fence(Ordering::SeqCst);
+        }
+    }
+
     /// Reads data from a readable object like a File and writes it to guest memory.
     ///
     /// # Arguments
@@ -400,6 +639,92 @@ impl MemoryMapping {
         Ok(())
     }
 
+    /// Returns a `VolatileSlice` for each `(offset, len)` pair in `regions`, in order, validated
+    /// against the bounds of this mapping. `read_to_memory_iovec` and `write_from_memory_iovec`
+    /// convert these slices into a `libc::iovec` array for the `preadv`/`pwritev` syscalls.
+    pub fn get_slices(&self, regions: &[(usize, usize)]) -> Result<Vec<VolatileSlice>> {
+        regions
+            .iter()
+            .map(|&(offset, len)| {
+                self.get_slice(offset as u64, len as u64)
+                    .map_err(|_| Error::InvalidRange(offset, len))
+            })
+            .collect()
+    }
+
+    /// Reads from `fd` at `file_offset` directly into the given memory regions with a single
+    /// `preadv` call. Returns the total number of bytes read.
+    ///
+    /// # Arguments
+    /// * `fd` - File descriptor to read from.
+    /// * `file_offset` - Offset in `fd` to start reading from.
+    /// * `regions` - Memory `(offset, len)` pairs to scatter the read into, in order.
+    pub fn read_to_memory_iovec(
+        &self,
+        fd: &AsRawFd,
+        file_offset: libc::off_t,
+        regions: &[(usize, usize)],
+    ) -> Result<usize> {
+        let iovecs = self.get_iovecs(regions)?;
+        // Safe because the iovecs were built from slices validated to lie within this mapping,
+        // and preadv will not write past the length given in each iovec.
+        let ret = unsafe {
+            libc::preadv(
+                fd.as_raw_fd(),
+                iovecs.as_ptr(),
+                iovecs.len() as c_int,
+                file_offset,
+            )
+        };
+        if ret < 0 {
+            return Err(Error::SystemCallFailed(errno::Error::last()));
+        }
+        Ok(ret as usize)
+    }
+
+    /// Writes the given memory regions to `fd` at `file_offset` with a single `pwritev` call.
+    /// Returns the total number of bytes written.
+    ///
+    /// # Arguments
+    /// * `fd` - File descriptor to write to.
+    /// * `file_offset` - Offset in `fd` to start writing at.
+    /// * `regions` - Memory `(offset, len)` pairs to gather the write from, in order.
+    pub fn write_from_memory_iovec(
+        &self,
+        fd: &AsRawFd,
+        file_offset: libc::off_t,
+        regions: &[(usize, usize)],
+    ) -> Result<usize> {
+        let iovecs = self.get_iovecs(regions)?;
+        // Safe because the iovecs were built from slices validated to lie within this mapping,
+        // and pwritev will not read past the length given in each iovec.
+        let ret = unsafe {
+            libc::pwritev(
+                fd.as_raw_fd(),
+                iovecs.as_ptr(),
+                iovecs.len() as c_int,
+                file_offset,
+            )
+        };
+        if ret < 0 {
+            return Err(Error::SystemCallFailed(errno::Error::last()));
+        }
+        Ok(ret as usize)
+    }
+
+    // Builds the `iovec` array backing `read_to_memory_iovec`/`write_from_memory_iovec` from the
+    // validated `VolatileSlice`s for `regions`.
+    fn get_iovecs(&self, regions: &[(usize, usize)]) -> Result<Vec<libc::iovec>> {
+        Ok(self
+            .get_slices(regions)?
+            .iter()
+            .map(|slice| libc::iovec {
+                iov_base: slice.as_ptr() as *mut libc::c_void,
+                iov_len: slice.size() as libc::size_t,
+            })
+            .collect())
+    }
+
     /// Uses madvise to tell the kernel to remove the specified range.  Subsequent reads
     /// to the pages in the range will return zero bytes.
     pub fn remove_range(&self, mem_offset: usize, count: usize) -> Result<()> {
@@ -421,6 +746,55 @@ impl MemoryMapping {
         }
     }
 
+    /// Advises the kernel on expected usage of the specified range, e.g. `MADV_DONTNEED` to let
+    /// a memory balloon device reclaim inflated pages, or `MADV_WILLNEED` to prefetch pages
+    /// that are about to be accessed.
+    ///
+    /// # Arguments
+    /// * `mem_offset` - Begin the advised range at this offset.
+    /// * `count` - Size in bytes of the advised range.
+    /// * `advice` - `madvise` advice, e.g. `libc::MADV_DONTNEED`.
+    pub fn advise_range(&self, mem_offset: usize, count: usize, advice: c_int) -> Result<()> {
+        self.range_end(mem_offset, count)
+            .map_err(|_| Error::InvalidRange(mem_offset, count))?;
+        let ret = unsafe {
+            // Safe because the range was validated against the bounds of this mapping above.
+            libc::madvise((self.addr as usize + mem_offset) as *mut _, count, advice)
+        };
+        if ret < 0 {
+            Err(Error::InvalidRange(mem_offset, count))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Queries residency of the pages covering `mem_offset..mem_offset+count`, returning one
+    /// `bool` per page in order, `true` if the page is currently resident in RAM. A live
+    /// migration precopy pass can use this to decide which dirty pages still need to be
+    /// transferred instead of always re-sending the full region.
+    ///
+    /// # Arguments
+    /// * `mem_offset` - Begin the queried range at this offset.
+    /// * `count` - Size in bytes of the queried range.
+    pub fn mincore_range(&self, mem_offset: usize, count: usize) -> Result<Vec<bool>> {
+        self.range_end(mem_offset, count)
+            .map_err(|_| Error::InvalidRange(mem_offset, count))?;
+
+        let page_size = page_size();
+        let start = (self.addr as usize + mem_offset) & !(page_size - 1);
+        let end = self.addr as usize + mem_offset + count;
+        let num_pages = (end - start + page_size - 1) / page_size;
+
+        let mut residency = vec![0u8; num_pages];
+        // Safe because `residency` has one byte for every page spanned by the queried range, and
+        // that range was validated against the bounds of this mapping above.
+        let ret = unsafe { libc::mincore(start as *mut _, end - start, residency.as_mut_ptr()) };
+        if ret < 0 {
+            return Err(Error::InvalidRange(mem_offset, count));
+        }
+        Ok(residency.iter().map(|&b| b & 1 != 0).collect())
+    }
+
     unsafe fn as_slice(&self) -> &[u8] {
         // This is safe because we mapped the area at addr ourselves, so this slice will not
         // overflow. However, it is possible to alias.
@@ -468,6 +842,181 @@ impl Drop for MemoryMapping {
     }
 }
 
+/// A `MemoryMapping` that reserves a fixed range of address space up front and then allows
+/// sub-regions of it to be mapped to and unmapped from file descriptors at runtime, without the
+/// base address of the reservation ever moving.
+///
+/// This is useful for things like hot-pluggable device memory (GPU host pointers, pmem windows)
+/// where a guest-physical window needs to stay at a stable address while the backing mappings
+/// underneath it come and go.
+#[derive(Debug)]
+pub struct MemoryMappingArena {
+    addr: *mut u8,
+    size: usize,
+    // Non-overlapping (offset, size) ranges, in the order they were added, that are currently
+    // mapped to something other than the PROT_NONE reservation.
+    regions: Vec<(usize, usize)>,
+}
+
+// Send and Sync aren't automatically inherited for the raw address pointer.
+// Accessing that pointer is only done through the stateless interface which
+// allows the object to be shared by multiple threads without a decrease in
+// safety.
+unsafe impl Send for MemoryMappingArena {}
+unsafe impl Sync for MemoryMappingArena {}
+
+impl MemoryMappingArena {
+    /// Reserves `size` bytes of address space, inaccessible until sub-regions of it are mapped
+    /// with `add_fd_offset`.
+    ///
+    /// # Arguments
+    /// * `size` - Size of the address space to reserve, in bytes.
+    pub fn new(size: usize) -> Result<MemoryMappingArena> {
+        // This is safe because we are creating an anonymous mapping in a place not already used
+        // by any other area in this process.
+        let addr = unsafe {
+            libc::mmap(
+                null_mut(),
+                size,
+                libc::PROT_NONE,
+                libc::MAP_ANONYMOUS | libc::MAP_PRIVATE | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(Error::SystemCallFailed(errno::Error::last()));
+        }
+        Ok(MemoryMappingArena {
+            addr: addr as *mut u8,
+            size,
+            regions: Vec::new(),
+        })
+    }
+
+    /// Returns the size of the reserved address range in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Maps `size` bytes of `fd` starting at `fd_offset` into this arena at `offset`, with
+    /// `prot` protection. `offset..offset+size` must lie within the arena and must not overlap
+    /// an existing sub-mapping.
+    ///
+    /// # Arguments
+    /// * `offset` - Offset within the arena to map at.
+    /// * `size` - Size in bytes of the region to map.
+    /// * `fd` - File descriptor to map from.
+    /// * `fd_offset` - Offset in bytes from the beginning of `fd` to start the mapping.
+    /// * `prot` - Protection (e.g. readable/writable) of the new mapping.
+    pub fn add_fd_offset(
+        &mut self,
+        offset: usize,
+        size: usize,
+        fd: &AsRawFd,
+        fd_offset: usize,
+        prot: Protection,
+    ) -> Result<()> {
+        if fd_offset > libc::off_t::max_value() as usize {
+            return Err(Error::InvalidOffset);
+        }
+        self.validate_range(offset, size)?;
+
+        // This is safe because MAP_FIXED backed by an fd replaces the PROT_NONE reservation we
+        // made for this exact range in `new`, so it can't clobber unrelated memory in this
+        // process.
+        let addr = unsafe {
+            libc::mmap(
+                (self.addr as usize + offset) as *mut libc::c_void,
+                size,
+                prot.into(),
+                libc::MAP_SHARED | libc::MAP_FIXED,
+                fd.as_raw_fd(),
+                fd_offset as libc::off_t,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(Error::SystemCallFailed(errno::Error::last()));
+        }
+
+        self.insert_region(offset, size);
+        Ok(())
+    }
+
+    /// Removes the sub-mapping at `offset..offset+size`, returning that range of address space
+    /// to its original `PROT_NONE` reservation. `offset..offset+size` must exactly match a
+    /// region previously passed to `add_fd_offset`.
+    pub fn remove(&mut self, offset: usize, size: usize) -> Result<()> {
+        // `validate_range` exists to reject overlap with an already-tracked region, which makes
+        // it unusable here: the region being removed is by definition already in `self.regions`
+        // and always overlaps itself. Require an exact match instead.
+        if !self
+            .regions
+            .iter()
+            .any(|&(r_off, r_size)| r_off == offset && r_size == size)
+        {
+            return Err(Error::InvalidRange(offset, size));
+        }
+
+        // This is safe because we are replacing the given range with an anonymous, inaccessible
+        // mapping that falls entirely within our own reservation from `new`.
+        let addr = unsafe {
+            libc::mmap(
+                (self.addr as usize + offset) as *mut libc::c_void,
+                size,
+                libc::PROT_NONE,
+                libc::MAP_ANONYMOUS | libc::MAP_PRIVATE | libc::MAP_FIXED | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(Error::SystemCallFailed(errno::Error::last()));
+        }
+
+        self.regions
+            .retain(|&(r_off, r_size)| (r_off, r_size) != (offset, size));
+        Ok(())
+    }
+
+    // Checks that `offset..offset+size` lies within the arena and doesn't overlap any
+    // currently-live sub-region. Used by `add_fd_offset`; `remove` needs an exact-match check
+    // instead, since the region it's removing is expected to already be tracked.
+    fn validate_range(&self, offset: usize, size: usize) -> Result<()> {
+        let end = self.range_end(offset, size)?;
+        for &(r_off, r_size) in &self.regions {
+            let r_end = r_off + r_size;
+            if offset < r_end && r_off < end {
+                return Err(Error::InvalidRange(offset, size));
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_region(&mut self, offset: usize, size: usize) {
+        self.regions.push((offset, size));
+    }
+
+    // Check that offset+count is valid and return the sum.
+    fn range_end(&self, offset: usize, count: usize) -> Result<usize> {
+        let mem_end = offset.checked_add(count).ok_or(Error::InvalidAddress)?;
+        if mem_end > self.size {
+            return Err(Error::InvalidAddress);
+        }
+        Ok(mem_end)
+    }
+}
+
+impl Drop for MemoryMappingArena {
+    fn drop(&mut self) {
+        // This is safe because we mmap the entire reservation at addr ourselves, and nobody else
+        // is holding a reference to it.
+        unsafe {
+            libc::munmap(self.addr as *mut libc::c_void, self.size);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -560,4 +1109,272 @@ mod tests {
             e => panic!("unexpected error: {}", e),
         }
     }
+
+    // Creates an anonymous, in-memory file of the given size to back arena sub-mappings in tests.
+    fn create_backing_file(size: u64) -> std::fs::File {
+        let name = std::ffi::CString::new("mmap_test").unwrap();
+        let fd = unsafe { libc::syscall(libc::SYS_memfd_create, name.as_ptr(), 0) };
+        assert!(fd >= 0);
+        let file = unsafe { std::fs::File::from_raw_fd(fd as i32) };
+        file.set_len(size).unwrap();
+        file
+    }
+
+    #[test]
+    fn arena_add_rejects_overlap() {
+        let mut arena = MemoryMappingArena::new(0x2000).unwrap();
+        let file = create_backing_file(0x1000);
+        arena
+            .add_fd_offset(0, 0x1000, &file, 0, Protection::read_write())
+            .unwrap();
+
+        let res = arena
+            .add_fd_offset(0x800, 0x1000, &file, 0, Protection::read_write())
+            .unwrap_err();
+        match res {
+            Error::InvalidRange(0x800, 0x1000) => {}
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn arena_remove_allows_remap() {
+        let mut arena = MemoryMappingArena::new(0x2000).unwrap();
+        let file = create_backing_file(0x1000);
+        arena
+            .add_fd_offset(0, 0x1000, &file, 0, Protection::read_write())
+            .unwrap();
+        arena.remove(0, 0x1000).unwrap();
+
+        // The range is no longer tracked as live, so it can be mapped again.
+        arena
+            .add_fd_offset(0, 0x1000, &file, 0, Protection::read_write())
+            .unwrap();
+    }
+
+    #[test]
+    fn arena_remove_requires_exact_match() {
+        let mut arena = MemoryMappingArena::new(0x2000).unwrap();
+        let file = create_backing_file(0x1000);
+        arena
+            .add_fd_offset(0, 0x1000, &file, 0, Protection::read_write())
+            .unwrap();
+
+        // A sub-range of a live region was never itself added, so it can't be removed directly.
+        let res = arena.remove(0, 0x800).unwrap_err();
+        match res {
+            Error::InvalidRange(0, 0x800) => {}
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn volatile_copy_roundtrip() {
+        let m = MemoryMapping::new(4096).unwrap();
+        let src: Vec<u8> = (0..4096).map(|i| (i % 256) as u8).collect();
+        m.copy_to_volatile(&src, 0).unwrap();
+
+        let mut dst = vec![0u8; 4096];
+        m.copy_from_volatile(&mut dst, 0).unwrap();
+        assert_eq!(src, dst);
+    }
+
+    #[test]
+    fn volatile_copy_unaligned() {
+        // An offset and length that don't land on cacheline boundaries exercise the byte-at-a-time
+        // head fragment in both directions of the copy (with 64-byte lines, all 37 bytes starting
+        // at offset 11 fall within the head fragment).
+        let m = MemoryMapping::new(4096).unwrap();
+        let src: Vec<u8> = (0..37).map(|i| (i * 7 + 3) as u8).collect();
+        m.copy_to_volatile(&src, 11).unwrap();
+
+        let mut dst = vec![0u8; 37];
+        m.copy_from_volatile(&mut dst, 11).unwrap();
+        assert_eq!(src, dst);
+    }
+
+    #[test]
+    fn volatile_copy_out_of_range() {
+        let m = MemoryMapping::new(5).unwrap();
+        let res = m.copy_to_volatile(&[1, 2, 3, 4, 5, 6], 0).unwrap_err();
+        match res {
+            Error::InvalidRange(0, 6) => {}
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn get_slices_scatter_gather() {
+        let m = MemoryMapping::new(1024).unwrap();
+        let slices = m.get_slices(&[(0, 16), (32, 8)]).unwrap();
+        assert_eq!(slices.len(), 2);
+        assert_eq!(slices[0].size(), 16);
+        assert_eq!(slices[1].size(), 8);
+        assert_eq!(slices[1].as_ptr(), unsafe { m.as_ptr().add(32) });
+    }
+
+    #[test]
+    fn get_slices_out_of_range() {
+        let m = MemoryMapping::new(1024).unwrap();
+        let res = m.get_slices(&[(1000, 100)]).unwrap_err();
+        match res {
+            Error::InvalidRange(1000, 100) => {}
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn write_from_memory_iovec_gathers_into_file() {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let m = MemoryMapping::new(64).unwrap();
+        m.write_slice(&[1, 2, 3, 4], 0).unwrap();
+        m.write_slice(&[5, 6, 7, 8], 32).unwrap();
+
+        let mut file = create_backing_file(64);
+        let written = m
+            .write_from_memory_iovec(&file, 0, &[(0, 4), (32, 4)])
+            .unwrap();
+        assert_eq!(written, 8);
+
+        let mut buf = [0u8; 8];
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn read_to_memory_iovec_scatters_from_file() {
+        use std::io::Write;
+
+        let mut file = create_backing_file(64);
+        file.write_all(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+
+        let m = MemoryMapping::new(64).unwrap();
+        let read = m
+            .read_to_memory_iovec(&file, 0, &[(0, 4), (32, 4)])
+            .unwrap();
+        assert_eq!(read, 8);
+
+        assert_eq!(
+            m.read_obj::<u32>(0).unwrap(),
+            u32::from_ne_bytes([1, 2, 3, 4])
+        );
+        assert_eq!(
+            m.read_obj::<u32>(32).unwrap(),
+            u32::from_ne_bytes([5, 6, 7, 8])
+        );
+    }
+
+    #[test]
+    fn from_fd_offset_protection_flags_private_is_cow() {
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        let mut file = create_backing_file(4096);
+        file.write_all(&[42u8; 4096]).unwrap();
+
+        let m = MemoryMapping::from_fd_offset_protection_flags(
+            &file,
+            4096,
+            0,
+            Protection::read_write(),
+            libc::MAP_PRIVATE,
+        )
+        .unwrap();
+        m.write_obj(0u8, 0).unwrap();
+        assert_eq!(m.read_obj::<u8>(0).unwrap(), 0);
+
+        // A MAP_PRIVATE mapping is copy-on-write: the write above must not reach the file.
+        let mut first_byte = [0u8; 1];
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_exact(&mut first_byte).unwrap();
+        assert_eq!(first_byte[0], 42);
+    }
+
+    #[test]
+    fn from_fd_offset_protection_flags_shared_writes_through() {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let file = create_backing_file(4096);
+        let m = MemoryMapping::from_fd_offset_protection_flags(
+            &file,
+            4096,
+            0,
+            Protection::read_write(),
+            libc::MAP_SHARED,
+        )
+        .unwrap();
+        m.write_obj(7u8, 0).unwrap();
+
+        let mut first_byte = [0u8; 1];
+        let mut file = file;
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_exact(&mut first_byte).unwrap();
+        assert_eq!(first_byte[0], 7);
+    }
+
+    #[test]
+    fn new_protection_hugetlb_falls_back_cleanly() {
+        // Huge pages are frequently not reserved in CI/sandbox environments, so this can't assert
+        // success. It only checks that a failure surfaces as a normal SystemCallFailed error
+        // rather than a crash, and that callers really can fall back to new_protection as the
+        // doc comment promises.
+        match MemoryMapping::new_protection_hugetlb(2 * 1024 * 1024, Protection::read_write(), None)
+        {
+            Ok(m) => assert_eq!(m.size(), 2 * 1024 * 1024),
+            Err(Error::SystemCallFailed(_)) => {
+                let m = MemoryMapping::new_protection(2 * 1024 * 1024, Protection::read_write())
+                    .unwrap();
+                assert_eq!(m.size(), 2 * 1024 * 1024);
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn mincore_range_reports_touched_page_resident() {
+        let m = MemoryMapping::new(2 * page_size()).unwrap();
+        // Touch the first page so the kernel has to back it with a real page.
+        m.write_obj(1u8, 0).unwrap();
+
+        let residency = m.mincore_range(0, page_size()).unwrap();
+        assert_eq!(residency.len(), 1);
+        assert!(residency[0]);
+    }
+
+    #[test]
+    fn advise_range_dontneed_then_mincore() {
+        // `MemoryMapping::new` memory is shared (shmem-backed) and its pages stay resident in the
+        // page cache after MADV_DONTNEED, so map /dev/zero privately to get private anonymous
+        // memory instead.
+        let zero = std::fs::File::open("/dev/zero").unwrap();
+        let m = MemoryMapping::from_fd_offset_protection_flags(
+            &zero,
+            page_size(),
+            0,
+            Protection::read_write(),
+            libc::MAP_PRIVATE,
+        )
+        .unwrap();
+        m.write_obj(1u8, 0).unwrap();
+        assert!(m.mincore_range(0, page_size()).unwrap()[0]);
+
+        m.advise_range(0, page_size(), libc::MADV_DONTNEED).unwrap();
+        // MADV_DONTNEED drops the page immediately for private anonymous memory, so it's no
+        // longer resident until it's touched again.
+        assert!(!m.mincore_range(0, page_size()).unwrap()[0]);
+    }
+
+    #[test]
+    fn advise_range_out_of_range() {
+        let m = MemoryMapping::new(page_size()).unwrap();
+        let res = m
+            .advise_range(page_size(), page_size(), libc::MADV_DONTNEED)
+            .unwrap_err();
+        match res {
+            Error::InvalidRange(offset, count) if offset == page_size() && count == page_size() => {
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
 }